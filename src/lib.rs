@@ -18,6 +18,9 @@
 
 extern crate rand;
 
+use std::cmp::Ordering;
+use std::rc::Rc;
+
 use thiserror::Error;
 
 /// Errors during directory interaction.
@@ -33,22 +36,55 @@ pub enum DirError<'a> {
     /// Traversal failed due to missing subdirectory.
     #[error("{0}: invalid element in path")]
     InvalidChild(&'a str),
+    /// `rmdir` was called on a directory that still has children.
+    #[error("{0}: directory not empty")]
+    NotEmpty(&'a str),
+    /// Following a symlink chain took more than [`MAX_SYMLINK_HOPS`] hops, which means it's
+    /// looping rather than bottoming out.
+    #[error("{0}: too many levels of symbolic links")]
+    LoopDetected(&'a str),
+    /// A `..` component in a path passed to [`normalize`] popped past the root.
+    #[error("{0}: `..` would escape the root")]
+    AboveRoot(&'a str),
 }
 
 /// Result type for directory errors.
 pub type Result<'a, T> = std::result::Result<T, DirError<'a>>;
 
+/// How many symlinks a single resolution chain will follow before giving up with
+/// [`DirError::LoopDetected`]. Bounding the hop count catches cycles (`a -> b`, `b -> a`) without
+/// having to track which directories have already been visited.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// What a [`DEnt`] names: a subdirectory, a file with its own contents, or a symlink pointing
+/// elsewhere in the tree.
+#[derive(Debug, Clone)]
+pub enum Node<'a> {
+    /// A subdirectory.
+    Dir(DTree<'a>),
+    /// A file, holding its raw contents.
+    File(Vec<u8>),
+    /// A symlink, holding the `/`-separated path it points at, relative to the directory
+    /// containing the link.
+    Symlink(&'a str),
+}
+
 /// A directory entry. Component names are stored externally.
 #[derive(Debug, Clone)]
 pub struct DEnt<'a> {
     pub name: &'a str,
-    pub subdir: DTree<'a>,
+    pub node: Node<'a>,
 }
 
 /// A directory tree.
+///
+/// Children are stored behind [`Rc`] so that cloning a `DTree` (as [`DTree::snapshot`] does) is
+/// cheap and shares every subtree with the original: cloning the `Vec` bumps a refcount per
+/// direct child instead of deep-copying. A mutating operation only allocates a fresh copy of the
+/// entries it actually changes, via [`Rc::make_mut`], leaving untouched siblings shared.
 #[derive(Debug, Clone, Default)]
 pub struct DTree<'a> {
-    pub children: Vec<DEnt<'a>>,
+    pub children: Vec<Rc<DEnt<'a>>>,
 }
 
 /// Operating system state: the directory tree and the current working directory.
@@ -65,15 +101,32 @@ impl<'a> DEnt<'a> {
         }
         Ok(Self {
             name,
-            subdir: DTree::new(),
+            node: Node::Dir(DTree::new()),
+        })
+    }
+
+    fn new_file(name: &'a str, contents: Vec<u8>) -> Result<Self> {
+        if name.contains('/') {
+            return Err(DirError::SlashInName(name));
+        }
+        Ok(Self {
+            name,
+            node: Node::File(contents),
         })
     }
+
     ///paths implementation for DEnt; makes navigating easier to do, and allows us to build path strings in the correct order / way
     fn paths(&self) -> Vec<String> {
+        let subdir = match &self.node {
+            Node::File(_) => return vec![self.name.to_string()],
+            Node::Symlink(target) => return vec![format!("{} -> {}", self.name, target)],
+            Node::Dir(subdir) => subdir,
+        };
+
         let mut pathvec: Vec<String> = Vec::new();
 
-        if !self.subdir.children.is_empty() {
-            for x in &self.subdir.children {
+        if !subdir.children.is_empty() {
+            for x in &subdir.children {
                 for y in x.paths() {
                     pathvec.push(self.name.to_string() + "/" + &y);
                 }
@@ -84,6 +137,38 @@ impl<'a> DEnt<'a> {
 
         pathvec
     }
+
+    /// Following counterpart of [`DEnt::paths`]: resolves symlinks to the paths of what they
+    /// point at, instead of rendering them as `name -> target`. `parent` is the directory this
+    /// entry lives in, which a symlink target is resolved relative to.
+    fn paths_following(&self, parent: &DTree<'a>, hops: usize) -> Result<'a, Vec<String>> {
+        let (subdir, hops) = match &self.node {
+            Node::File(_) => return Ok(vec![self.name.to_string()]),
+            Node::Symlink(target) => {
+                if hops >= MAX_SYMLINK_HOPS {
+                    return Err(DirError::LoopDetected(self.name));
+                }
+                let remaining: Vec<&'a str> =
+                    target.split('/').rev().filter(|s| !s.is_empty()).collect();
+                parent.subdir_following(remaining, hops + 1, |d| d)?
+            }
+            Node::Dir(subdir) => (subdir, hops),
+        };
+
+        let mut pathvec: Vec<String> = Vec::new();
+
+        if !subdir.children.is_empty() {
+            for x in &subdir.children {
+                for y in x.paths_following(subdir, hops)? {
+                    pathvec.push(self.name.to_string() + "/" + &y);
+                }
+            }
+        } else {
+            pathvec.push(self.name.to_string() + "/");
+        }
+
+        Ok(pathvec)
+    }
 }
 
 impl<'a> DTree<'a> {
@@ -119,7 +204,264 @@ impl<'a> DTree<'a> {
         }
 
         let entry = DEnt::new(name).unwrap();
-        self.children.push(entry);
+        self.children.push(Rc::new(entry));
+        Ok(())
+    }
+
+    /// Create a file with the given name and contents in this directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::DTree;
+    /// let mut dt = DTree::new();
+    /// dt.create_file("test", b"hi".to_vec()).unwrap();
+    /// assert_eq!(&dt.paths(), &["/test"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::SlashInName` if `name` contains `/`.
+    /// * `DirError::DirExists` if `name` already exists, whether as a file or a directory.
+    pub fn create_file(&mut self, name: &'a str, contents: impl Into<Vec<u8>>) -> Result<'a, ()> {
+        if name.contains('/') {
+            return Err(DirError::SlashInName(name));
+        }
+
+        for x in &self.children {
+            if x.name == name {
+                return Err(DirError::DirExists(name));
+            }
+        }
+
+        let entry = DEnt::new_file(name, contents.into()).unwrap();
+        self.children.push(Rc::new(entry));
+        Ok(())
+    }
+
+    /// Create a symlink with the given name, pointing at `target`: a `/`-separated path,
+    /// resolved relative to this directory when it's followed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::DTree;
+    /// let mut dt = DTree::new();
+    /// dt.mkdir("a").unwrap();
+    /// dt.symlink("link", "a").unwrap();
+    /// assert_eq!(&dt.paths(), &["/a/", "/link -> a"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::SlashInName` if `name` contains `/`.
+    /// * `DirError::DirExists` if `name` already exists, whether as a file, directory, or symlink.
+    pub fn symlink(&mut self, name: &'a str, target: &'a str) -> Result<'a, ()> {
+        if name.contains('/') {
+            return Err(DirError::SlashInName(name));
+        }
+
+        for x in &self.children {
+            if x.name == name {
+                return Err(DirError::DirExists(name));
+            }
+        }
+
+        self.children.push(Rc::new(DEnt {
+            name,
+            node: Node::Symlink(target),
+        }));
+        Ok(())
+    }
+
+    /// Walk down `path` from this directory, following only subdirectories.
+    ///
+    /// Unlike `with_subdir`, this takes an explicit-lifetime path and an empty `path` returns
+    /// `self`, which is what the file and rename/copy operations below need when their target is
+    /// a direct child of the starting directory.
+    fn navigate<'b>(&'b self, path: &[&'a str]) -> Result<'a, &'b DTree<'a>> {
+        let mut dir = self;
+        for &name in path {
+            match dir.children.iter().find(|x| x.name == name).map(|x| &x.node) {
+                Some(Node::Dir(subdir)) => dir = subdir,
+                _ => return Err(DirError::InvalidChild(name)),
+            }
+        }
+        Ok(dir)
+    }
+
+    /// Mutable counterpart of [`DTree::navigate`]. Every entry along `path` is copy-on-write:
+    /// [`Rc::make_mut`] only clones an entry (and so its ancestors on the way down) if it's still
+    /// shared with another snapshot.
+    fn navigate_mut<'b>(&'b mut self, path: &[&'a str]) -> Result<'a, &'b mut DTree<'a>> {
+        let mut dir = self;
+        for &name in path {
+            let idx = dir
+                .children
+                .iter()
+                .position(|x| x.name == name)
+                .ok_or(DirError::InvalidChild(name))?;
+            if !matches!(dir.children[idx].node, Node::Dir(_)) {
+                return Err(DirError::InvalidChild(name));
+            }
+            match &mut Rc::make_mut(&mut dir.children[idx]).node {
+                Node::Dir(subdir) => dir = subdir,
+                _ => unreachable!(),
+            }
+        }
+        Ok(dir)
+    }
+
+    /// Read the contents of the file at `path`, relative to this directory.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if `path` doesn't lead to a file.
+    pub fn read_file(&self, path: &[&'a str]) -> Result<'a, &[u8]> {
+        let (&name, dir_path) = path.split_last().ok_or(DirError::InvalidChild(""))?;
+        let dir = self.navigate(dir_path)?;
+
+        match dir.children.iter().find(|x| x.name == name).map(|x| &x.node) {
+            Some(Node::File(contents)) => Ok(contents),
+            _ => Err(DirError::InvalidChild(name)),
+        }
+    }
+
+    /// Write `contents` to the file at `path`, relative to this directory, creating it if it
+    /// doesn't already exist.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if an ancestor directory in `path` doesn't exist.
+    /// * `DirError::DirExists` if `path` names an existing directory.
+    pub fn write_file(&mut self, path: &[&'a str], contents: impl Into<Vec<u8>>) -> Result<'a, ()> {
+        let (&name, dir_path) = path.split_last().ok_or(DirError::InvalidChild(""))?;
+
+        // Validate before mutating, so a failed write leaves the tree untouched.
+        if matches!(
+            self.navigate(dir_path)?.children.iter().find(|x| x.name == name),
+            Some(existing) if !matches!(existing.node, Node::File(_))
+        ) {
+            return Err(DirError::DirExists(name));
+        }
+
+        let dir = self.navigate_mut(dir_path)?;
+        match dir.children.iter().position(|x| x.name == name) {
+            Some(idx) => {
+                match &mut Rc::make_mut(&mut dir.children[idx]).node {
+                    Node::File(existing) => *existing = contents.into(),
+                    _ => unreachable!(),
+                }
+                Ok(())
+            }
+            None => dir.create_file(name, contents),
+        }
+    }
+
+    /// Remove the leaf directory at `path`, relative to this directory.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if `path` doesn't lead to an existing directory.
+    /// * `DirError::NotEmpty` if the directory at `path` has any children.
+    pub fn rmdir(&mut self, path: &[&'a str]) -> Result<'a, ()> {
+        let (&name, dir_path) = path.split_last().ok_or(DirError::InvalidChild(""))?;
+
+        // Validate before mutating, so a failed rmdir leaves the tree untouched.
+        match self.navigate(dir_path)?.children.iter().find(|x| x.name == name).map(|x| &x.node) {
+            Some(Node::Dir(subdir)) if subdir.children.is_empty() => {}
+            Some(Node::Dir(_)) => return Err(DirError::NotEmpty(name)),
+            _ => return Err(DirError::InvalidChild(name)),
+        }
+
+        let dir = self.navigate_mut(dir_path)?;
+        let idx = dir.children.iter().position(|x| x.name == name).unwrap();
+        dir.children.remove(idx);
+        Ok(())
+    }
+
+    /// Remove the subtree at `path`, relative to this directory, regardless of its contents.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if `path` doesn't lead to an existing entry.
+    pub fn remove_all(&mut self, path: &[&'a str]) -> Result<'a, ()> {
+        let (&name, dir_path) = path.split_last().ok_or(DirError::InvalidChild(""))?;
+
+        // Validate before mutating, so a failed remove_all leaves the tree untouched.
+        if !self.navigate(dir_path)?.children.iter().any(|x| x.name == name) {
+            return Err(DirError::InvalidChild(name));
+        }
+
+        let dir = self.navigate_mut(dir_path)?;
+        let idx = dir.children.iter().position(|x| x.name == name).unwrap();
+        dir.children.remove(idx);
+        Ok(())
+    }
+
+    /// Detach the subtree at `from` and re-attach it under `to`, both relative to this
+    /// directory.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if `from` doesn't lead to an existing entry, an ancestor
+    /// directory in `to` doesn't exist, or `to` would place the entry inside its own subtree.
+    /// * `DirError::DirExists` if `to` already exists.
+    pub fn rename(&mut self, from: &[&'a str], to: &[&'a str]) -> Result<'a, ()> {
+        let (&from_name, from_parent) = from.split_last().ok_or(DirError::InvalidChild(""))?;
+        let (&to_name, to_parent) = to.split_last().ok_or(DirError::InvalidChild(""))?;
+
+        if to_parent.len() >= from.len() && to_parent[..from.len()] == *from {
+            return Err(DirError::InvalidChild(from_name));
+        }
+
+        // Validate both ends before mutating anything, so a failed rename leaves the tree
+        // untouched.
+        if !self.navigate(from_parent)?.children.iter().any(|x| x.name == from_name) {
+            return Err(DirError::InvalidChild(from_name));
+        }
+        if self.navigate(to_parent)?.children.iter().any(|x| x.name == to_name) {
+            return Err(DirError::DirExists(to_name));
+        }
+
+        let from_dir = self.navigate_mut(from_parent)?;
+        let idx = from_dir.children.iter().position(|x| x.name == from_name).unwrap();
+        let mut entry = from_dir.children.remove(idx);
+        Rc::make_mut(&mut entry).name = to_name;
+
+        self.navigate_mut(to_parent)?.children.push(entry);
+        Ok(())
+    }
+
+    /// Copy the subtree at `from` to a new location at `to`, both relative to this directory.
+    /// The copy shares its subtrees with the original (see [`DTree`]), so mutating either one
+    /// afterwards leaves the other unchanged.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if `from` doesn't lead to an existing entry, or an ancestor
+    /// directory in `to` doesn't exist.
+    /// * `DirError::DirExists` if `to` already exists.
+    pub fn copy(&mut self, from: &[&'a str], to: &[&'a str]) -> Result<'a, ()> {
+        let (&from_name, from_parent) = from.split_last().ok_or(DirError::InvalidChild(""))?;
+        let (&to_name, to_parent) = to.split_last().ok_or(DirError::InvalidChild(""))?;
+
+        let mut entry = self
+            .navigate(from_parent)?
+            .children
+            .iter()
+            .find(|x| x.name == from_name)
+            .cloned()
+            .ok_or(DirError::InvalidChild(from_name))?;
+        Rc::make_mut(&mut entry).name = to_name;
+
+        // Validate the destination before mutating anything, so a failed copy leaves the tree
+        // untouched.
+        if self.navigate(to_parent)?.children.iter().any(|x| x.name == to_name) {
+            return Err(DirError::DirExists(to_name));
+        }
+
+        self.navigate_mut(to_parent)?.children.push(entry);
         Ok(())
     }
 
@@ -163,7 +505,85 @@ impl<'a> DTree<'a> {
 
         for x in &self.children {
             if x.name == name {
-                return x.subdir.subdir(path, f);
+                return match &x.node {
+                    Node::Dir(subdir) => subdir.subdir(path, f),
+                    Node::File(_) | Node::Symlink(_) => Err(DirError::InvalidChild(name)),
+                };
+            }
+        }
+
+        Err(DirError::InvalidChild(name))
+    }
+
+    /// Like [`DTree::with_subdir`], but transparently follows symlinks encountered while
+    /// descending, instead of treating them as opaque leaves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::DTree;
+    /// let mut dt = DTree::new();
+    /// dt.mkdir("a").unwrap();
+    /// dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap()).unwrap();
+    /// dt.symlink("link", "a").unwrap();
+    /// let paths = dt.with_subdir_following(&["link"], |dt| dt.paths()).unwrap();
+    /// assert_eq!(&paths, &["/b/"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if `path` is invalid.
+    /// * `DirError::LoopDetected` if following a symlink along `path` doesn't terminate within
+    /// [`MAX_SYMLINK_HOPS`] hops.
+    pub fn with_subdir_following<'b, F, R>(&'b self, path: &[&'a str], f: F) -> Result<'a, R>
+    where
+        F: FnOnce(&'b DTree<'a>) -> R,
+    {
+        if path.is_empty() {
+            return Err(DirError::InvalidChild(""));
+        }
+
+        let paths: Vec<&'a str> = path.iter().rev().cloned().collect();
+        self.subdir_following(paths, 0, f).map(|(r, _)| r)
+    }
+
+    /// Recursive portion of `with_subdir_following`; see [`DTree::subdir`] for the non-following
+    /// counterpart this mirrors.
+    ///
+    /// Returns the hop count reached alongside `f`'s result, instead of just the result, so that
+    /// a caller resolving one symlink at a time (like [`DEnt::paths_following`] or `WalkDir`) can
+    /// keep counting hops already spent resolving this one, rather than restarting at `0` and
+    /// letting a link that points back into its own ancestry recurse forever.
+    fn subdir_following<'b, F, R>(
+        &'b self,
+        mut path: Vec<&'a str>,
+        hops: usize,
+        f: F,
+    ) -> Result<'a, (R, usize)>
+    where
+        F: FnOnce(&'b DTree<'a>) -> R,
+    {
+        if path.is_empty() {
+            return Ok((f(self), hops));
+        }
+
+        let name = path.pop().unwrap();
+
+        for x in &self.children {
+            if x.name == name {
+                return match &x.node {
+                    Node::Dir(subdir) => subdir.subdir_following(path, hops, f),
+                    Node::Symlink(target) => {
+                        if hops >= MAX_SYMLINK_HOPS {
+                            return Err(DirError::LoopDetected(name));
+                        }
+                        for part in target.split('/').rev().filter(|s| !s.is_empty()) {
+                            path.push(part);
+                        }
+                        self.subdir_following(path, hops + 1, f)
+                    }
+                    Node::File(_) => Err(DirError::InvalidChild(name)),
+                };
             }
         }
 
@@ -194,6 +614,10 @@ impl<'a> DTree<'a> {
             return Err(DirError::InvalidChild("empty path"));
         }
 
+        // Validate the whole path before cloning any of it, so a failed traversal (as opposed to
+        // a failure inside `f` itself) leaves the tree untouched.
+        self.navigate(path)?;
+
         let paths: Vec<&'a str> = path.iter().rev().cloned().collect();
 
         self.subdir_mut(paths, f)
@@ -210,13 +634,91 @@ impl<'a> DTree<'a> {
 
         let name = path.pop().unwrap();
 
-        for x in &mut self.children {
-            if x.name == name {
-                return x.subdir.subdir_mut(path, f);
+        let idx = self
+            .children
+            .iter()
+            .position(|x| x.name == name)
+            .ok_or(DirError::InvalidChild(name))?;
+
+        // Check before cloning, so a path through a non-directory (or missing entry) leaves the
+        // tree untouched instead of force-cloning it on the way to an error.
+        if !matches!(self.children[idx].node, Node::Dir(_)) {
+            return Err(DirError::InvalidChild(name));
+        }
+
+        match &mut Rc::make_mut(&mut self.children[idx]).node {
+            Node::Dir(subdir) => subdir.subdir_mut(path, f),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Like [`DTree::with_subdir_mut`], but transparently follows symlinks encountered while
+    /// descending, instead of treating them as opaque leaves.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if `path` is invalid.
+    /// * `DirError::LoopDetected` if following a symlink along `path` doesn't terminate within
+    /// [`MAX_SYMLINK_HOPS`] hops.
+    pub fn with_subdir_mut_following<'b, F, R>(&'b mut self, path: &[&'a str], f: F) -> Result<'a, R>
+    where
+        F: FnOnce(&'b mut DTree<'a>) -> R,
+    {
+        if path.is_empty() {
+            return Err(DirError::InvalidChild("empty path"));
+        }
+
+        // Validate the whole path before cloning any of it, so a failed traversal (as opposed to
+        // a failure inside `f` itself) leaves the tree untouched.
+        self.with_subdir_following(path, |_| ())?;
+
+        let paths: Vec<&'a str> = path.iter().rev().cloned().collect();
+        self.subdir_mut_following(paths, 0, f)
+    }
+
+    ///Recursive portion of with_subdir_mut_following; see [`DTree::subdir_mut`] for the
+    ///non-following counterpart this mirrors.
+    fn subdir_mut_following<'b, F, R>(
+        &'b mut self,
+        mut path: Vec<&'a str>,
+        hops: usize,
+        f: F,
+    ) -> Result<'a, R>
+    where
+        F: FnOnce(&'b mut DTree<'a>) -> R,
+    {
+        if path.is_empty() {
+            return Ok(f(self));
+        }
+
+        let name = path.pop().unwrap();
+        let idx = self
+            .children
+            .iter()
+            .position(|x| x.name == name)
+            .ok_or(DirError::InvalidChild(name))?;
+
+        if let Node::Symlink(target) = &self.children[idx].node {
+            if hops >= MAX_SYMLINK_HOPS {
+                return Err(DirError::LoopDetected(name));
             }
+            let target = *target;
+            for part in target.split('/').rev().filter(|s| !s.is_empty()) {
+                path.push(part);
+            }
+            return self.subdir_mut_following(path, hops + 1, f);
         }
 
-        Err(DirError::InvalidChild(name))
+        // Check before cloning, so a path through a file leaves the tree untouched instead of
+        // force-cloning it on the way to an error.
+        if !matches!(self.children[idx].node, Node::Dir(_)) {
+            return Err(DirError::InvalidChild(name));
+        }
+
+        match &mut Rc::make_mut(&mut self.children[idx]).node {
+            Node::Dir(subdir) => subdir.subdir_mut_following(path, hops, f),
+            _ => unreachable!("symlinks are handled above"),
+        }
     }
 
     /// Produce a list of the paths to each reachable leaf, in no particular order.  Path
@@ -244,41 +746,434 @@ impl<'a> DTree<'a> {
         }
         pathvec
     }
-}
-
-impl<'a> OsState<'a> {
-    /// Create a new directory tree in the operating system.  Current working directory is the
-    /// root.
-    pub fn new() -> Self {
-        Self::default()
-    }
 
-    /// If `path` is empty, change the working directory to the root.  Otherwise change the
-    /// working directory to the subdirectory given by `path` relative to the current working
-    /// directory.  (There is no notion of `.` or `..`: `path` must be a valid sequence of
-    /// component names.)
+    /// Following counterpart of [`DTree::paths`]: resolves symlinks to the paths of what they
+    /// point at, instead of rendering them as `name -> target`.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use dtree::OsState;
-    /// let mut s = OsState::new();
-    /// s.mkdir("a").unwrap();
-    /// s.chdir(&["a"]).unwrap();
-    /// s.mkdir("b").unwrap();
-    /// s.chdir(&["b"]).unwrap();
-    /// s.mkdir("c").unwrap();
-    /// s.chdir(&[]).unwrap();
-    /// assert_eq!(&s.paths().unwrap(), &["/a/b/c/"]);
+    /// # use dtree::DTree;
+    /// let mut dt = DTree::new();
+    /// dt.mkdir("a").unwrap();
+    /// dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap()).unwrap();
+    /// dt.symlink("link", "a").unwrap();
+    /// let mut paths = dt.paths_following().unwrap();
+    /// paths.sort();
+    /// assert_eq!(&paths, &["/a/b/", "/link/b/"]);
     /// ```
     ///
     /// # Errors
     ///
-    /// * `DirError::InvalidChild` if the new working directory is invalid. On error, the original
-    /// working directory will be retained.
-    pub fn chdir(&mut self, path: &[&'a str]) -> Result<()> {
-        if path.is_empty() {
-            self.cwd.clear();
+    /// * `DirError::LoopDetected` if following a symlink doesn't terminate within
+    /// [`MAX_SYMLINK_HOPS`] hops.
+    pub fn paths_following(&self) -> Result<'a, Vec<String>> {
+        let mut pathvec: Vec<String> = Vec::new();
+
+        for x in &self.children {
+            for y in x.paths_following(self, 0)? {
+                pathvec.push("/".to_owned() + &y);
+            }
+        }
+        Ok(pathvec)
+    }
+
+    /// Produce a cheap snapshot of this tree: an independent `DTree` that still shares every
+    /// subtree with `self`, since [`DTree`]'s children are held behind [`Rc`]. Mutating either
+    /// the snapshot or `self` afterwards only clones the entries actually touched, via
+    /// [`Rc::make_mut`], leaving the other copy untouched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::DTree;
+    /// let mut dt = DTree::new();
+    /// dt.mkdir("a").unwrap();
+    /// let snap = dt.snapshot();
+    /// dt.mkdir("b").unwrap();
+    /// assert_eq!(&snap.paths(), &["/a/"]);
+    /// assert_eq!(&dt.paths(), &["/a/", "/b/"]);
+    /// ```
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Compare this tree against `other`, reporting which leaf paths were added or removed.
+    ///
+    /// Subtrees that are still [`Rc::ptr_eq`] between the two trees are skipped without being
+    /// walked, since [`DTree::snapshot`]'s structural sharing already guarantees they're
+    /// identical; only the parts of the tree that have actually diverged are visited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::{Change, DTree};
+    /// let mut dt = DTree::new();
+    /// dt.mkdir("a").unwrap();
+    /// let snap = dt.snapshot();
+    /// dt.mkdir("b").unwrap();
+    /// assert_eq!(snap.diff(&dt), &[Change::Added("/b/".to_string())]);
+    /// ```
+    pub fn diff(&self, other: &DTree<'a>) -> Vec<Change> {
+        let mut changes = Vec::new();
+        diff_into(self, other, "", &mut changes);
+        changes
+    }
+
+    /// Return a lazy, streaming walk over this tree and its subdirectories, in the style of the
+    /// `walkdir` crate.
+    ///
+    /// Unlike [`DTree::paths`], which eagerly collects every leaf path into a `Vec`, `WalkDir`
+    /// holds an explicit stack of frames and advances one step per call to `next`, so large
+    /// trees can be processed without allocating the whole result up front. The root of the tree
+    /// is yielded with an empty name at depth `0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::DTree;
+    /// let mut dt = DTree::new();
+    /// dt.mkdir("a").unwrap();
+    /// dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap()).unwrap();
+    /// let names: Vec<_> = dt.walk().map(|entry| entry.name.to_string()).collect();
+    /// assert_eq!(names, &["", "a", "b"]);
+    /// ```
+    pub fn walk<'b>(&'b self) -> WalkDir<'b, 'a> {
+        WalkDir::new(self)
+    }
+}
+
+/// A single difference between two directory trees, as produced by [`DTree::diff`]. Paths are
+/// rendered the same way [`DTree::paths`] renders them: a trailing `/` for a directory, a bare
+/// name for a file, and `name -> target` for an unfollowed symlink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A leaf present in the newer tree but not the older one.
+    Added(String),
+    /// A leaf present in the older tree but not the newer one.
+    Removed(String),
+}
+
+/// Recursive portion of [`DTree::diff`]: walks `old` and `new` side by side, appending a
+/// [`Change`] for every path that differs between them. `prefix` is the path, without a trailing
+/// `/`, to the directory `old`/`new` are the children of.
+fn diff_into<'a>(old: &DTree<'a>, new: &DTree<'a>, prefix: &str, changes: &mut Vec<Change>) {
+    for old_child in &old.children {
+        match new.children.iter().find(|x| x.name == old_child.name) {
+            Some(new_child) if Rc::ptr_eq(old_child, new_child) => {}
+            Some(new_child) => diff_entry(old_child, new_child, prefix, changes),
+            None => changes.extend(removed_paths(old_child, prefix)),
+        }
+    }
+
+    for new_child in &new.children {
+        if !old.children.iter().any(|x| x.name == new_child.name) {
+            changes.extend(added_paths(new_child, prefix));
+        }
+    }
+}
+
+/// Diff two same-named entries. Two directories are recursed into so only what actually changed
+/// underneath them is reported; anything else that differs (a file's contents, a symlink's
+/// target, or a kind change like a file replacing a directory) is reported as a removal of the
+/// old leaf paths and an addition of the new ones.
+fn diff_entry<'a>(old: &DEnt<'a>, new: &DEnt<'a>, prefix: &str, changes: &mut Vec<Change>) {
+    match (&old.node, &new.node) {
+        (Node::Dir(old_dir), Node::Dir(new_dir)) => {
+            let child_prefix = format!("{}/{}", prefix, old.name);
+            diff_into(old_dir, new_dir, &child_prefix, changes);
+            return;
+        }
+        (Node::File(a), Node::File(b)) if a == b => return,
+        (Node::Symlink(a), Node::Symlink(b)) if a == b => return,
+        _ => {}
+    }
+
+    changes.extend(removed_paths(old, prefix));
+    changes.extend(added_paths(new, prefix));
+}
+
+fn removed_paths(entry: &DEnt, prefix: &str) -> Vec<Change> {
+    entry
+        .paths()
+        .into_iter()
+        .map(|p| Change::Removed(format!("{}/{}", prefix, p)))
+        .collect()
+}
+
+fn added_paths(entry: &DEnt, prefix: &str) -> Vec<Change> {
+    entry
+        .paths()
+        .into_iter()
+        .map(|p| Change::Added(format!("{}/{}", prefix, p)))
+        .collect()
+}
+
+/// An entry yielded by [`WalkDir`], describing one directory visited during the walk.
+#[derive(Debug, Clone)]
+pub struct WalkEntry<'a> {
+    /// The directory's own name, or `""` for the root of the walk.
+    pub name: &'a str,
+    /// The full path from the root of the walk, `/`-separated.
+    pub path: String,
+    /// How many directories deep this entry is; the root is depth `0`.
+    pub depth: usize,
+}
+
+/// A comparator used by [`WalkDir::sort_by`] to order a directory's children.
+type SortFn<'b, 'a> = Box<dyn FnMut(&DEnt<'a>, &DEnt<'a>) -> Ordering + 'b>;
+
+/// One level of the explicit stack `WalkDir` uses instead of recursing.
+struct WalkFrame<'b, 'a> {
+    dir: &'b DTree<'a>,
+    name: &'a str,
+    path: String,
+    depth: usize,
+    order: Vec<usize>,
+    order_computed: bool,
+    index: usize,
+    pre_emitted: bool,
+    /// How many symlinks were followed to reach `dir`. Carried forward into frames pushed for
+    /// its children so that a chain of re-entries into the same resolved directory (e.g. a
+    /// symlink pointing at its own containing directory) still counts hops instead of each
+    /// re-entry restarting at `0` and recursing forever.
+    hops: usize,
+}
+
+/// A lazy, iterative depth-first walk over a [`DTree`]. Built with [`DTree::walk`].
+///
+/// Traversal is iterative rather than recursive: `WalkDir` keeps an explicit stack of
+/// `(directory, child-index)` frames, and each call to `next` either descends into the next
+/// child of the top frame or pops it once its children are exhausted. File entries are skipped;
+/// only directories are yielded and descended into. Symlinks are skipped too, unless
+/// [`WalkDir::follow_links`] is set, in which case one that resolves to a directory (without
+/// looping) is descended into as if it were one.
+pub struct WalkDir<'b, 'a> {
+    stack: Vec<WalkFrame<'b, 'a>>,
+    min_depth: usize,
+    max_depth: usize,
+    sort_by: Option<SortFn<'b, 'a>>,
+    contents_first: bool,
+    follow_links: bool,
+}
+
+impl<'b, 'a> WalkDir<'b, 'a> {
+    fn new(dir: &'b DTree<'a>) -> Self {
+        let root = WalkFrame {
+            dir,
+            name: "",
+            path: String::new(),
+            depth: 0,
+            order: Vec::new(),
+            order_computed: false,
+            index: 0,
+            pre_emitted: false,
+            hops: 0,
+        };
+        Self {
+            stack: vec![root],
+            min_depth: 0,
+            max_depth: usize::MAX,
+            sort_by: None,
+            contents_first: false,
+            follow_links: false,
+        }
+    }
+
+    /// Don't yield entries shallower than `depth`; the root is depth `0`.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Don't descend into directories deeper than `depth`; the root is depth `0`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Order each directory's children with `cmp` before visiting them.
+    pub fn sort_by<F>(mut self, cmp: F) -> Self
+    where
+        F: FnMut(&DEnt<'a>, &DEnt<'a>) -> Ordering + 'b,
+    {
+        self.sort_by = Some(Box::new(cmp));
+        self
+    }
+
+    /// Yield a directory only after all of its descendants have been yielded, instead of before.
+    pub fn contents_first(mut self, yes: bool) -> Self {
+        self.contents_first = yes;
+        self
+    }
+
+    /// Follow symlinks while descending, instead of treating them as opaque leaves. A link whose
+    /// target doesn't resolve to a directory within [`MAX_SYMLINK_HOPS`] hops (such as an
+    /// `a -> b`, `b -> a` cycle) is not descended into. A link that resolves to one of its own
+    /// ancestors is re-entered, and yielded, once per hop until that bound is reached.
+    pub fn follow_links(mut self, yes: bool) -> Self {
+        self.follow_links = yes;
+        self
+    }
+}
+
+impl<'b, 'a> Iterator for WalkDir<'b, 'a> {
+    type Item = WalkEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let top = self.stack.len().checked_sub(1)?;
+
+            if !self.contents_first && !self.stack[top].pre_emitted {
+                self.stack[top].pre_emitted = true;
+                let frame = &self.stack[top];
+                if frame.depth >= self.min_depth {
+                    return Some(WalkEntry {
+                        name: frame.name,
+                        path: frame.path.clone(),
+                        depth: frame.depth,
+                    });
+                }
+                continue;
+            }
+
+            if !self.stack[top].order_computed {
+                let dir = self.stack[top].dir;
+                let mut order: Vec<usize> = (0..dir.children.len()).collect();
+                if let Some(cmp) = &mut self.sort_by {
+                    order.sort_by(|&i, &j| cmp(&dir.children[i], &dir.children[j]));
+                }
+                self.stack[top].order = order;
+                self.stack[top].order_computed = true;
+            }
+
+            let can_descend = self.stack[top].depth < self.max_depth;
+            if can_descend && self.stack[top].index < self.stack[top].order.len() {
+                let frame = &mut self.stack[top];
+                let child_pos = frame.order[frame.index];
+                frame.index += 1;
+                let child = &frame.dir.children[child_pos];
+                let child_dir = match &child.node {
+                    Node::Dir(child_dir) => Some((child_dir, frame.hops)),
+                    Node::Symlink(target) if self.follow_links => {
+                        if frame.hops >= MAX_SYMLINK_HOPS {
+                            None
+                        } else {
+                            frame
+                                .dir
+                                .subdir_following(
+                                    target.split('/').rev().filter(|s| !s.is_empty()).collect(),
+                                    frame.hops + 1,
+                                    |d| d,
+                                )
+                                .ok()
+                        }
+                    }
+                    Node::Symlink(_) | Node::File(_) => None,
+                };
+                if let Some((child_dir, child_hops)) = child_dir {
+                    let child_path = format!("{}/{}", frame.path, child.name);
+                    let child_depth = frame.depth + 1;
+                    self.stack.push(WalkFrame {
+                        dir: child_dir,
+                        name: child.name,
+                        path: child_path,
+                        depth: child_depth,
+                        order: Vec::new(),
+                        order_computed: false,
+                        index: 0,
+                        pre_emitted: false,
+                        hops: child_hops,
+                    });
+                }
+                continue;
+            }
+
+            let frame = self.stack.pop().unwrap();
+            if self.contents_first && frame.depth >= self.min_depth {
+                return Some(WalkEntry {
+                    name: frame.name,
+                    path: frame.path,
+                    depth: frame.depth,
+                });
+            }
+        }
+    }
+}
+
+/// Resolve `input` to an absolute sequence of component names, the way a shell resolves a path
+/// argument: splitting on `/`, dropping `.` components, popping a component off `cwd` for each
+/// `..`, and resolving an absolute path (one starting with `/`) against the root instead of
+/// `cwd`. `cwd` and the returned components are plain slice/name components, the same form
+/// [`DTree`] and [`OsState`] use everywhere else; there is still no notion of `.` or `..` once a
+/// path has been normalized.
+///
+/// # Examples
+///
+/// ```
+/// # use dtree::normalize;
+/// assert_eq!(normalize(&["a", "b"], "../c").unwrap(), vec!["a", "c"]);
+/// assert_eq!(normalize(&["a", "b"], "/x/y").unwrap(), vec!["x", "y"]);
+/// ```
+///
+/// # Errors
+///
+/// * `DirError::AboveRoot` if a `..` component would pop past the root.
+pub fn normalize<'a>(cwd: &[&'a str], input: &'a str) -> Result<'a, Vec<&'a str>> {
+    let mut stack: Vec<&'a str> = if input.starts_with('/') {
+        Vec::new()
+    } else {
+        cwd.to_vec()
+    };
+
+    for part in input.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if stack.pop().is_none() {
+                    return Err(DirError::AboveRoot(input));
+                }
+            }
+            name => stack.push(name),
+        }
+    }
+
+    Ok(stack)
+}
+
+impl<'a> OsState<'a> {
+    /// Create a new directory tree in the operating system.  Current working directory is the
+    /// root.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// If `path` is empty, change the working directory to the root.  Otherwise change the
+    /// working directory to the subdirectory given by `path` relative to the current working
+    /// directory.  (There is no notion of `.` or `..`: `path` must be a valid sequence of
+    /// component names.)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::OsState;
+    /// let mut s = OsState::new();
+    /// s.mkdir("a").unwrap();
+    /// s.chdir(&["a"]).unwrap();
+    /// s.mkdir("b").unwrap();
+    /// s.chdir(&["b"]).unwrap();
+    /// s.mkdir("c").unwrap();
+    /// s.chdir(&[]).unwrap();
+    /// assert_eq!(&s.paths().unwrap(), &["/a/b/c/"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if the new working directory is invalid. On error, the original
+    /// working directory will be retained.
+    pub fn chdir(&mut self, path: &[&'a str]) -> Result<()> {
+        if path.is_empty() {
+            self.cwd.clear();
         } else {
             match self
                 .dtree
@@ -309,11 +1204,12 @@ impl<'a> OsState<'a> {
             return self.dtree.mkdir(name);
         }
 
-        let mut pathvec = self.cwd.clone();
-        pathvec.reverse();
+        // Validate before mutating, so a failed mkdir leaves the tree untouched.
+        if self.dtree.navigate(&self.cwd)?.children.iter().any(|x| x.name == name) {
+            return Err(DirError::DirExists(name));
+        }
 
-        self.dtree
-            .subdir_mut(pathvec, |dtree| dtree.mkdir(name).unwrap())
+        self.dtree.navigate_mut(&self.cwd)?.mkdir(name)
     }
 
     /// Produce a list of the paths from the working directory to each reachable leaf, in no
@@ -332,6 +1228,135 @@ impl<'a> OsState<'a> {
 
         self.dtree.subdir(pathvec, |dir| dir.paths())
     }
+
+    /// Remove the leaf directory at `path`, relative to the working directory.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if the working directory or `path` is invalid.
+    /// * `DirError::NotEmpty` if the directory at `path` has any children.
+    pub fn rmdir(&mut self, path: &[&'a str]) -> Result<()> {
+        let full_path: Vec<&'a str> = self.cwd.iter().chain(path).copied().collect();
+        self.dtree.rmdir(&full_path)
+    }
+
+    /// Move the entry at `from` to `to`, both relative to the working directory.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if the working directory or `from` is invalid.
+    /// * `DirError::DirExists` if `to` already exists.
+    pub fn rename(&mut self, from: &[&'a str], to: &[&'a str]) -> Result<()> {
+        let full_from: Vec<&'a str> = self.cwd.iter().chain(from).copied().collect();
+        let full_to: Vec<&'a str> = self.cwd.iter().chain(to).copied().collect();
+        self.dtree.rename(&full_from, &full_to)
+    }
+
+    /// Copy the entry at `from` to a new location at `to`, both relative to the working
+    /// directory, as [`DTree::copy`] does.
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::InvalidChild` if the working directory or `from` is invalid.
+    /// * `DirError::DirExists` if `to` already exists.
+    pub fn cp(&mut self, from: &[&'a str], to: &[&'a str]) -> Result<()> {
+        let full_from: Vec<&'a str> = self.cwd.iter().chain(from).copied().collect();
+        let full_to: Vec<&'a str> = self.cwd.iter().chain(to).copied().collect();
+        self.dtree.copy(&full_from, &full_to)
+    }
+
+    /// Like [`OsState::chdir`], but takes a single `/`-separated string, [`normalize`]d against
+    /// the working directory instead of a pre-tokenized, `.`/`..`-free component slice. An
+    /// absolute `input` (starting with `/`) is resolved against the root instead of the working
+    /// directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::OsState;
+    /// let mut s = OsState::new();
+    /// s.mkdir("a").unwrap();
+    /// s.chdir(&["a"]).unwrap();
+    /// s.mkdir("b").unwrap();
+    /// s.chdir_str("./b/..").unwrap();
+    /// assert_eq!(&s.paths().unwrap(), &["/b/"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::AboveRoot` if `input` contains a `..` that would pop past the root.
+    /// * `DirError::InvalidChild` if the resulting working directory is invalid. On error, the
+    /// original working directory will be retained.
+    pub fn chdir_str(&mut self, input: &'a str) -> Result<()> {
+        let path = normalize(&self.cwd, input)?;
+        if path.is_empty() {
+            self.cwd.clear();
+        } else {
+            self.dtree.with_subdir(&path, |_| {})?;
+            self.cwd = path;
+        }
+        Ok(())
+    }
+
+    /// Like [`OsState::mkdir`], but takes a single `/`-separated string, [`normalize`]d against
+    /// the working directory, naming the directory to create; its parent must already exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::OsState;
+    /// let mut s = OsState::new();
+    /// s.mkdir("a").unwrap();
+    /// s.mkdir_str("a/b").unwrap();
+    /// assert_eq!(&s.paths().unwrap(), &["/a/b/"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::AboveRoot` if `input` contains a `..` that would pop past the root.
+    /// * `DirError::SlashInName` if the final component contains `/` (impossible, since `/` is
+    /// what separates components, but kept for parity with [`OsState::mkdir`]).
+    /// * `DirError::InvalidChild` if an ancestor directory doesn't exist.
+    /// * `DirError::DirExists` if the resulting name already exists.
+    pub fn mkdir_str(&mut self, input: &'a str) -> Result<()> {
+        let path = normalize(&self.cwd, input)?;
+        let (&name, parent) = path.split_last().ok_or(DirError::InvalidChild(input))?;
+
+        // Validate before mutating, so a failed mkdir_str leaves the tree untouched.
+        if self.dtree.navigate(parent)?.children.iter().any(|x| x.name == name) {
+            return Err(DirError::DirExists(name));
+        }
+
+        self.dtree.navigate_mut(parent)?.mkdir(name)
+    }
+
+    /// Like [`OsState::paths`], but lists the paths reachable from `input`, a single
+    /// `/`-separated string [`normalize`]d against the working directory, instead of from the
+    /// working directory itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use dtree::OsState;
+    /// let mut s = OsState::new();
+    /// s.mkdir("a").unwrap();
+    /// s.chdir(&["a"]).unwrap();
+    /// s.mkdir("b").unwrap();
+    /// assert_eq!(&s.paths_from("..").unwrap(), &["/a/b/"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `DirError::AboveRoot` if `input` contains a `..` that would pop past the root.
+    /// * `DirError::InvalidChild` if `input` doesn't resolve to an existing directory.
+    pub fn paths_from(&self, input: &'a str) -> Result<Vec<String>> {
+        let path = normalize(&self.cwd, input)?;
+        if path.is_empty() {
+            Ok(self.dtree.paths())
+        } else {
+            self.dtree.with_subdir(&path, |dir| dir.paths())
+        }
+    }
 }
 
 #[cfg(test)]
@@ -445,3 +1470,663 @@ mod osstate_tests {
         s.mkdir("a").unwrap();
     }
 }
+
+///Tests for WalkDir
+#[cfg(test)]
+mod walk_tests {
+    use crate::DTree;
+
+    fn sample() -> DTree<'static> {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.mkdir("z").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("c").unwrap())
+            .unwrap();
+        dt
+    }
+
+    ///Test that a plain walk visits the root first, then descends depth-first into each child.
+    #[test]
+    fn walk_pre_order() {
+        let dt = sample();
+        let paths: Vec<_> = dt.walk().map(|e| e.path).collect();
+        assert_eq!(paths, &["", "/a", "/a/b", "/a/c", "/z"]);
+    }
+
+    ///Test that contents_first yields a directory only after its descendants.
+    #[test]
+    fn walk_contents_first() {
+        let dt = sample();
+        let paths: Vec<_> = dt.walk().contents_first(true).map(|e| e.path).collect();
+        assert_eq!(paths, &["/a/b", "/a/c", "/a", "/z", ""]);
+    }
+
+    ///Test that min_depth and max_depth bound which entries are yielded and visited.
+    #[test]
+    fn walk_depth_bounds() {
+        let dt = sample();
+        let paths: Vec<_> = dt
+            .walk()
+            .min_depth(1)
+            .max_depth(1)
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, &["/a", "/z"]);
+    }
+
+    ///Test that sort_by controls the order children are visited in.
+    #[test]
+    fn walk_sort_by() {
+        let dt = sample();
+        let paths: Vec<_> = dt
+            .walk()
+            .sort_by(|a, b| b.name.cmp(a.name))
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, &["", "/z", "/a", "/a/c", "/a/b"]);
+    }
+}
+
+///Tests for file entries
+#[cfg(test)]
+mod file_tests {
+    use crate::DTree;
+
+    #[test]
+    fn create_and_read_file() {
+        let mut dt = DTree::new();
+        dt.create_file("test", b"hello".to_vec()).unwrap();
+        assert_eq!(dt.read_file(&["test"]).unwrap(), b"hello");
+    }
+
+    ///Test that paths() marks file leaves without a trailing slash, unlike directory leaves.
+    #[test]
+    fn file_paths_have_no_trailing_slash() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.create_file("f", b"x".to_vec()).unwrap())
+            .unwrap();
+        assert_eq!(&dt.paths(), &["/a/f"]);
+    }
+
+    #[test]
+    fn write_file_creates_then_overwrites() {
+        let mut dt = DTree::new();
+        dt.write_file(&["test"], b"one".to_vec()).unwrap();
+        assert_eq!(dt.read_file(&["test"]).unwrap(), b"one");
+        dt.write_file(&["test"], b"two".to_vec()).unwrap();
+        assert_eq!(dt.read_file(&["test"]).unwrap(), b"two");
+    }
+
+    ///Test that mkdir refuses to shadow an existing file.
+    #[test]
+    #[should_panic]
+    fn mkdir_conflicts_with_file() {
+        let mut dt = DTree::new();
+        dt.create_file("a", b"x".to_vec()).unwrap();
+        dt.mkdir("a").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn cannot_descend_into_file() {
+        let mut dt = DTree::new();
+        dt.create_file("a", b"x".to_vec()).unwrap();
+        dt.with_subdir(&["a", "b"], |dt| dt.paths()).unwrap();
+    }
+}
+
+///Tests for removal, rename, and copy operations
+#[cfg(test)]
+mod mutate_tests {
+    use crate::{DTree, OsState};
+
+    #[test]
+    fn rmdir_removes_empty_directory() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.rmdir(&["a"]).unwrap();
+        assert_eq!(&dt.paths(), &[] as &[String]);
+    }
+
+    ///Test that rmdir refuses to remove a directory that still has children.
+    #[test]
+    #[should_panic]
+    fn rmdir_refuses_non_empty_directory() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.rmdir(&["a"]).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn rmdir_missing_directory() {
+        let mut dt = DTree::new();
+        dt.rmdir(&["a"]).unwrap();
+    }
+
+    ///Test that remove_all deletes a subtree regardless of its contents.
+    #[test]
+    fn remove_all_removes_non_empty_directory() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.remove_all(&["a"]).unwrap();
+        assert_eq!(&dt.paths(), &[] as &[String]);
+    }
+
+    #[test]
+    fn rename_moves_subtree() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.mkdir("z").unwrap();
+        dt.rename(&["a"], &["z", "a"]).unwrap();
+        assert_eq!(&dt.paths(), &["/z/a/b/"]);
+    }
+
+    ///Test that rename refuses to overwrite an existing entry at the destination.
+    #[test]
+    #[should_panic]
+    fn rename_refuses_existing_destination() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.mkdir("b").unwrap();
+        dt.rename(&["a"], &["b"]).unwrap();
+    }
+
+    ///Test that rename refuses to move a directory into its own descendant.
+    #[test]
+    #[should_panic]
+    fn rename_refuses_own_descendant() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.rename(&["a"], &["a", "b", "c"]).unwrap();
+    }
+
+    ///Test that copy duplicates a subtree, leaving the original untouched and independent.
+    #[test]
+    fn copy_clones_subtree_independently() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.copy(&["a"], &["z"]).unwrap();
+        dt.with_subdir_mut(&["z"], |dt| dt.mkdir("c").unwrap())
+            .unwrap();
+        let mut paths = dt.paths();
+        paths.sort();
+        assert_eq!(&paths, &["/a/b/", "/z/b/", "/z/c/"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_refuses_existing_destination() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.mkdir("b").unwrap();
+        dt.copy(&["a"], &["b"]).unwrap();
+    }
+
+    ///Test that OsState's rmdir/rename/cp resolve paths relative to the working directory.
+    #[test]
+    fn osstate_mutate_relative_to_cwd() {
+        let mut s = OsState::new();
+        s.mkdir("a").unwrap();
+        s.chdir(&["a"]).unwrap();
+        s.mkdir("b").unwrap();
+        s.mkdir("c").unwrap();
+        s.rename(&["b"], &["d"]).unwrap();
+        s.cp(&["d"], &["e"]).unwrap();
+        s.rmdir(&["c"]).unwrap();
+        let mut paths = s.paths().unwrap();
+        paths.sort();
+        assert_eq!(&paths, &["/d/", "/e/"]);
+    }
+
+    ///Test that chdir into a directory that was just removed fails cleanly.
+    #[test]
+    #[should_panic]
+    fn osstate_chdir_into_removed_directory() {
+        let mut s = OsState::new();
+        s.mkdir("a").unwrap();
+        s.rmdir(&["a"]).unwrap();
+        s.chdir(&["a"]).unwrap();
+    }
+}
+
+///Tests for symlinks and link-following traversal
+#[cfg(test)]
+mod symlink_tests {
+    use crate::{DTree, MAX_SYMLINK_HOPS};
+
+    ///Test that an unfollowed symlink is rendered as an opaque `name -> target` leaf.
+    #[test]
+    fn unfollowed_symlink_renders_as_arrow() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.symlink("link", "a").unwrap();
+        let mut paths = dt.paths();
+        paths.sort();
+        assert_eq!(&paths, &["/a/", "/link -> a"]);
+    }
+
+    #[test]
+    fn with_subdir_following_resolves_symlink() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.symlink("link", "a").unwrap();
+        let paths = dt.with_subdir_following(&["link"], |dt| dt.paths()).unwrap();
+        assert_eq!(&paths, &["/b/"]);
+    }
+
+    ///Test that a symlink can chain through another symlink to reach a directory.
+    #[test]
+    fn with_subdir_following_chains_through_symlinks() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.symlink("link1", "a").unwrap();
+        dt.symlink("link2", "link1").unwrap();
+        let paths = dt.with_subdir_following(&["link2"], |dt| dt.paths()).unwrap();
+        assert_eq!(&paths, &["/b/"]);
+    }
+
+    ///Test that a two-symlink cycle is rejected with LoopDetected instead of looping forever.
+    #[test]
+    #[should_panic]
+    fn cyclic_symlinks_are_detected() {
+        let mut dt = DTree::new();
+        dt.symlink("a", "b").unwrap();
+        dt.symlink("b", "a").unwrap();
+        dt.with_subdir_following(&["a"], |dt| dt.paths()).unwrap();
+    }
+
+    ///Test that a symlink with an empty target resolves to its own containing directory,
+    ///without being mistaken for a cycle.
+    #[test]
+    fn symlink_to_containing_directory_is_not_a_cycle() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.symlink("here", "").unwrap())
+            .unwrap();
+        let paths = dt
+            .with_subdir_following(&["a", "here"], |dt| dt.paths())
+            .unwrap();
+        assert_eq!(&paths, &["/here -> "]);
+    }
+
+    ///Test that WalkDir skips symlinks by default, the same as it skips files.
+    #[test]
+    fn walk_skips_symlinks_by_default() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.symlink("link", "a").unwrap();
+        let names: Vec<_> = dt.walk().map(|e| e.name.to_string()).collect();
+        assert_eq!(names, &["", "a"]);
+    }
+
+    ///Test that WalkDir::follow_links descends into a symlink that resolves to a directory.
+    #[test]
+    fn walk_follow_links_descends_into_symlink() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.symlink("link", "a").unwrap();
+        let paths: Vec<_> = dt
+            .walk()
+            .follow_links(true)
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, &["", "/a", "/a/b", "/link", "/link/b"]);
+    }
+
+    ///Test that WalkDir::follow_links doesn't get stuck on a cyclic symlink.
+    #[test]
+    fn walk_follow_links_stops_at_cycle() {
+        let mut dt = DTree::new();
+        dt.symlink("a", "b").unwrap();
+        dt.symlink("b", "a").unwrap();
+        let paths: Vec<_> = dt
+            .walk()
+            .follow_links(true)
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, &[""]);
+    }
+
+    ///Test that paths_following resolves symlinks instead of rendering them with `->`.
+    #[test]
+    fn paths_following_resolves_symlinks() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.symlink("link", "a").unwrap();
+        let mut paths = dt.paths_following().unwrap();
+        paths.sort();
+        assert_eq!(&paths, &["/a/b/", "/link/b/"]);
+    }
+
+    ///Test that paths_following reports a cyclic symlink as LoopDetected rather than looping.
+    #[test]
+    #[should_panic]
+    fn paths_following_detects_cycle() {
+        let mut dt = DTree::new();
+        dt.symlink("a", "b").unwrap();
+        dt.symlink("b", "a").unwrap();
+        dt.paths_following().unwrap();
+    }
+
+    ///Test that a symlink pointing at its own containing directory is detected as a cycle by
+    ///paths_following, rather than recursing into that directory (and so the symlink itself)
+    ///forever. Regression test: hop count must be threaded across re-entries into the resolved
+    ///directory, not just within a single symlink's own resolution.
+    #[test]
+    #[should_panic]
+    fn paths_following_detects_self_referencing_symlink() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.symlink("self", "").unwrap())
+            .unwrap();
+        dt.paths_following().unwrap();
+    }
+
+    ///Test that a symlink pointing at its own containing directory stops WalkDir::follow_links
+    ///within MAX_SYMLINK_HOPS re-entries instead of descending into that directory (and so the
+    ///symlink itself) forever. Regression test: see
+    ///paths_following_detects_self_referencing_symlink. Unlike that case, WalkDir's Iterator
+    ///can't surface a LoopDetected error, so each re-entry is still emitted as its own entry up
+    ///to the hop bound; what matters here is that the walk terminates at all.
+    #[test]
+    fn walk_follow_links_stops_at_self_referencing_symlink() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.symlink("self", "").unwrap())
+            .unwrap();
+        let paths: Vec<_> = dt
+            .walk()
+            .follow_links(true)
+            .map(|e| e.path)
+            .collect();
+        assert!(
+            paths.len() <= MAX_SYMLINK_HOPS + 2,
+            "walk should stop following the self-referencing symlink within MAX_SYMLINK_HOPS, got {} entries",
+            paths.len()
+        );
+    }
+
+    #[test]
+    fn symlink_conflicts_with_existing_name() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        assert!(dt.symlink("a", "x").is_err());
+    }
+}
+
+///Tests for copy-on-write snapshots and diffing
+#[cfg(test)]
+mod snapshot_tests {
+    use crate::{Change, DTree};
+    use std::rc::Rc;
+
+    ///Test that mutating a tree after taking a snapshot leaves the snapshot untouched.
+    #[test]
+    fn snapshot_is_independent_of_later_mutation() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        let snap = dt.snapshot();
+        dt.mkdir("b").unwrap();
+        assert_eq!(&snap.paths(), &["/a/"]);
+        assert_eq!(&dt.paths(), &["/a/", "/b/"]);
+    }
+
+    ///Test that mutating a snapshot doesn't affect the tree it was taken from.
+    #[test]
+    fn mutating_snapshot_does_not_affect_original() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        let mut snap = dt.snapshot();
+        snap.mkdir("b").unwrap();
+        assert_eq!(&dt.paths(), &["/a/"]);
+        assert_eq!(&snap.paths(), &["/a/", "/b/"]);
+    }
+
+    ///Test that an untouched subtree is still the same Rc allocation after a snapshot, which is
+    ///what lets diff skip walking it.
+    #[test]
+    fn snapshot_shares_untouched_subtrees() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        let snap = dt.snapshot();
+        let original = dt.children.iter().find(|x| x.name == "a").unwrap();
+        let shared = snap.children.iter().find(|x| x.name == "a").unwrap();
+        assert!(Rc::ptr_eq(original, shared));
+    }
+
+    #[test]
+    fn diff_of_identical_trees_is_empty() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        let snap = dt.snapshot();
+        assert_eq!(dt.diff(&snap), &[] as &[Change]);
+    }
+
+    #[test]
+    fn diff_reports_added_directory() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        let snap = dt.snapshot();
+        dt.mkdir("b").unwrap();
+        assert_eq!(snap.diff(&dt), &[Change::Added("/b/".to_string())]);
+    }
+
+    #[test]
+    fn diff_reports_removed_directory() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.mkdir("b").unwrap();
+        let snap = dt.snapshot();
+        dt.rmdir(&["b"]).unwrap();
+        assert_eq!(snap.diff(&dt), &[Change::Removed("/b/".to_string())]);
+    }
+
+    ///Test that a changed file's contents are reported as a removal of the old leaf and an
+    ///addition of the new one.
+    #[test]
+    fn diff_reports_changed_file_as_removed_and_added() {
+        let mut dt = DTree::new();
+        dt.create_file("f", b"old".to_vec()).unwrap();
+        let snap = dt.snapshot();
+        dt.write_file(&["f"], b"new".to_vec()).unwrap();
+        let changes = snap.diff(&dt);
+        assert_eq!(
+            &changes,
+            &[Change::Removed("/f".to_string()), Change::Added("/f".to_string())]
+        );
+    }
+
+    ///Test that diff recurses into unchanged directories, reporting only the leaf that actually
+    ///changed rather than the whole ancestor chain.
+    #[test]
+    fn diff_recurses_into_nested_directories() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("b").unwrap())
+            .unwrap();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("c").unwrap())
+            .unwrap();
+        let snap = dt.snapshot();
+        dt.with_subdir_mut(&["a"], |dt| dt.mkdir("d").unwrap())
+            .unwrap();
+        assert_eq!(snap.diff(&dt), &[Change::Added("/a/d/".to_string())]);
+    }
+
+    ///Test that diff treats a directory replaced by a file (same name, different kind) as a
+    ///removal of the directory's contents and an addition of the file.
+    #[test]
+    fn diff_reports_kind_change_as_removed_and_added() {
+        let mut dt = DTree::new();
+        dt.mkdir("a").unwrap();
+        let snap = dt.snapshot();
+        dt.remove_all(&["a"]).unwrap();
+        dt.create_file("a", b"x".to_vec()).unwrap();
+        let mut changes = snap.diff(&dt);
+        changes.sort_by_key(|c| match c {
+            Change::Added(p) | Change::Removed(p) => p.clone(),
+        });
+        assert_eq!(
+            changes,
+            &[Change::Added("/a".to_string()), Change::Removed("/a/".to_string())]
+        );
+    }
+
+    ///Regression test: a failed mutation beneath a directory should never force a clone of that
+    ///directory (or any ancestor above it) if nothing actually changed. `rename` fails here
+    ///because its source doesn't exist, `rmdir` because its target doesn't, and `with_subdir_mut`
+    ///because the path passes through a child that doesn't exist, so `"p"` should stay the same
+    ///Rc allocation as in `snap` after each.
+    #[test]
+    fn failed_nested_mutation_preserves_ancestor_sharing() {
+        let mut dt = DTree::new();
+        dt.mkdir("p").unwrap();
+        dt.with_subdir_mut(&["p"], |dt| dt.mkdir("child").unwrap())
+            .unwrap();
+        let snap = dt.snapshot();
+
+        assert!(dt.rename(&["p", "missing"], &["q"]).is_err());
+        let original = dt.children.iter().find(|x| x.name == "p").unwrap();
+        let shared = snap.children.iter().find(|x| x.name == "p").unwrap();
+        assert!(Rc::ptr_eq(original, shared));
+
+        assert!(dt.rmdir(&["p", "missing"]).is_err());
+        let original = dt.children.iter().find(|x| x.name == "p").unwrap();
+        let shared = snap.children.iter().find(|x| x.name == "p").unwrap();
+        assert!(Rc::ptr_eq(original, shared));
+
+        assert!(dt
+            .with_subdir_mut(&["p", "missing"], |dir| dir.mkdir("x").unwrap())
+            .is_err());
+        let original = dt.children.iter().find(|x| x.name == "p").unwrap();
+        let shared = snap.children.iter().find(|x| x.name == "p").unwrap();
+        assert!(Rc::ptr_eq(original, shared));
+    }
+
+    #[test]
+    fn diff_is_empty_between_clones_with_no_shared_allocations() {
+        let mut a = DTree::new();
+        a.mkdir("x").unwrap();
+        let mut b = DTree::new();
+        b.mkdir("x").unwrap();
+        assert_eq!(a.diff(&b), &[] as &[Change]);
+    }
+}
+
+///Tests for path normalization and the *_str entry points built on it
+#[cfg(test)]
+mod normalize_tests {
+    use crate::{normalize, OsState};
+
+    #[test]
+    fn normalize_drops_dot_components() {
+        assert_eq!(normalize(&["a"], "./b/./c").unwrap(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn normalize_pops_a_component_per_dotdot() {
+        assert_eq!(normalize(&["a", "b"], "../c").unwrap(), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn normalize_resolves_absolute_paths_against_root() {
+        assert_eq!(normalize(&["a", "b"], "/x/y").unwrap(), vec!["x", "y"]);
+    }
+
+    #[test]
+    fn normalize_of_dot_from_root_is_empty() {
+        assert_eq!(normalize(&[], ".").unwrap(), Vec::<&str>::new());
+    }
+
+    ///Test that a `..` which would pop past the root is rejected instead of silently clamping.
+    #[test]
+    #[should_panic]
+    fn normalize_above_root_is_an_error() {
+        normalize(&["a"], "../..").unwrap();
+    }
+
+    #[test]
+    fn normalize_collapses_repeated_slashes() {
+        assert_eq!(normalize(&[], "a//b").unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn chdir_str_navigates_relative_path() {
+        let mut s = OsState::new();
+        s.mkdir("a").unwrap();
+        s.chdir(&["a"]).unwrap();
+        s.mkdir("b").unwrap();
+        s.chdir_str("./b/..").unwrap();
+        assert_eq!(&s.paths().unwrap(), &["/b/"]);
+    }
+
+    #[test]
+    fn chdir_str_resolves_absolute_path() {
+        let mut s = OsState::new();
+        s.mkdir("a").unwrap();
+        s.chdir(&["a"]).unwrap();
+        s.mkdir("b").unwrap();
+        s.chdir_str("/a").unwrap();
+        assert_eq!(&s.paths().unwrap(), &["/b/"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn chdir_str_above_root_fails() {
+        let mut s = OsState::new();
+        s.chdir_str("..").unwrap();
+    }
+
+    #[test]
+    fn mkdir_str_creates_nested_directory() {
+        let mut s = OsState::new();
+        s.mkdir("a").unwrap();
+        s.mkdir_str("a/b").unwrap();
+        assert_eq!(&s.paths().unwrap(), &["/a/b/"]);
+    }
+
+    ///Test that mkdir_str refuses to create a directory whose parent doesn't exist.
+    #[test]
+    #[should_panic]
+    fn mkdir_str_missing_parent_fails() {
+        let mut s = OsState::new();
+        s.mkdir_str("a/b").unwrap();
+    }
+
+    #[test]
+    fn paths_from_lists_paths_relative_to_input() {
+        let mut s = OsState::new();
+        s.mkdir("a").unwrap();
+        s.chdir(&["a"]).unwrap();
+        s.mkdir("b").unwrap();
+        assert_eq!(&s.paths_from("..").unwrap(), &["/a/b/"]);
+    }
+
+    #[test]
+    fn paths_from_dot_matches_paths() {
+        let mut s = OsState::new();
+        s.mkdir("a").unwrap();
+        assert_eq!(s.paths_from(".").unwrap(), s.paths().unwrap());
+    }
+}